@@ -0,0 +1,211 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Single-producer/single-consumer ring buffer whose `push`/`drain` take `&self`,
+/// so unlike `CircularBuffer` it can live in a `static` and be shared between a
+/// producer thread and a consumer thread without a mutex.
+///
+/// Capacity is `N` elements, backed by an `N+1`-slot array so that `start == end`
+/// unambiguously means "empty" (a full buffer is `wrap(end+1) == start`).
+///
+/// Only one thread may call `push` (via the `Writer`) and only one thread may call
+/// `drain`/`is_empty` (via the `Reader`) at a time; mixing that up is a race.
+pub struct AtomicRingBuffer<T, const N: usize> {
+    buffer: AtomicPtr<T>,
+    start: AtomicUsize,
+    end: AtomicUsize,
+}
+
+impl<T, const N: usize> AtomicRingBuffer<T, N> {
+    /// Constructs an uninitialized ring buffer, suitable for a `static`.
+    /// Call `init` once, before `split` is used from any thread.
+    pub const fn uninit() -> Self {
+        Self {
+            buffer: AtomicPtr::new(ptr::null_mut()),
+            start: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+        }
+    }
+
+    fn wrap(i: usize) -> usize {
+        i % (N + 1)
+    }
+}
+
+impl<T: Default + Copy, const N: usize> AtomicRingBuffer<T, N> {
+    /// Leaks an `N+1`-element backing array and installs it. Must be called exactly
+    /// once, before any `Writer`/`Reader` obtained from `split` is used.
+    pub fn init(&self) {
+        let boxed: Box<[T]> = vec![T::default(); N + 1].into_boxed_slice();
+        let ptr = Box::into_raw(boxed) as *mut T;
+        self.buffer.store(ptr, Ordering::Release);
+    }
+
+    /// Returns the `Writer`/`Reader` handles. Only one thread should use each.
+    pub fn split(&self) -> (Writer<'_, T, N>, Reader<'_, T, N>) {
+        (Writer { ring: self }, Reader { ring: self })
+    }
+
+    fn slice(&self) -> &[T] {
+        let ptr = self.buffer.load(Ordering::Acquire);
+        unsafe { std::slice::from_raw_parts(ptr, N + 1) }
+    }
+
+    fn slice_mut(&self) -> &mut [T] {
+        let ptr = self.buffer.load(Ordering::Acquire);
+        unsafe { std::slice::from_raw_parts_mut(ptr, N + 1) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.load(Ordering::Acquire) == self.end.load(Ordering::Acquire)
+    }
+
+    pub fn is_full(&self) -> bool {
+        let end = self.end.load(Ordering::Acquire);
+        Self::wrap(end + 1) == self.start.load(Ordering::Acquire)
+    }
+}
+
+/// Producer handle: the only side allowed to call `push`.
+pub struct Writer<'a, T, const N: usize> {
+    ring: &'a AtomicRingBuffer<T, N>,
+}
+
+impl<'a, T: Default + Copy, const N: usize> Writer<'a, T, N> {
+    /// Pushes `value`, dropping it and returning `false` if the buffer is full.
+    pub fn push(&self, value: T) -> bool {
+        let start = self.ring.start.load(Ordering::Acquire);
+        let end = self.ring.end.load(Ordering::Relaxed);
+        let next = AtomicRingBuffer::<T, N>::wrap(end + 1);
+        if next == start {
+            return false; // full
+        }
+        self.ring.slice_mut()[end] = value;
+        self.ring.end.store(next, Ordering::Release);
+        true
+    }
+
+    /// Publishes `value` as the latest value for a `Reader::latest` caller,
+    /// never dropping it and never blocking on a full buffer the way `push`
+    /// does. Always writes into the slot at the current `end`, then advances
+    /// `end` with `Release` - double buffering, since that slot is not the one
+    /// `Reader::latest` reads (the slot right *behind* `end`). A `latest` read
+    /// that is still copying its slot is only overwritten if `N+1` publishes
+    /// land before it finishes (two, for `N = 1`), since only then does the
+    /// writer wrap back around to that slot. Pair with `Reader::latest`, not `drain`, and use this on
+    /// a single-slot "latest value" ring such as a published snapshot, not a
+    /// multi-element queue.
+    pub fn publish_latest(&self, value: T) {
+        let end = self.ring.end.load(Ordering::Relaxed);
+        self.ring.slice_mut()[end] = value;
+        let next = AtomicRingBuffer::<T, N>::wrap(end + 1);
+        self.ring.end.store(next, Ordering::Release);
+    }
+}
+
+/// Consumer handle: the only side allowed to call `drain`.
+pub struct Reader<'a, T, const N: usize> {
+    ring: &'a AtomicRingBuffer<T, N>,
+}
+
+impl<'a, T: Default + Copy, const N: usize> Reader<'a, T, N> {
+    /// Calls `f` with the elements pushed since the last `drain`, oldest first,
+    /// split into two slices to account for the wrap, then advances past them.
+    /// `f` sees an empty pair of slices if nothing has arrived.
+    pub fn drain<R>(&self, f: impl FnOnce(&[T], &[T]) -> R) -> R {
+        let start = self.ring.start.load(Ordering::Relaxed);
+        let end = self.ring.end.load(Ordering::Acquire);
+        let buffer = self.ring.slice();
+
+        let r = if start <= end {
+            f(&buffer[start..end], &[])
+        } else {
+            let (tail, head) = buffer.split_at(start);
+            f(head, tail.split_at(end).0)
+        };
+
+        self.ring.start.store(end, Ordering::Release);
+        r
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Reads the most recent value from `Writer::publish_latest`, without
+    /// draining or touching `start`. The writer's next publish targets the slot
+    /// at `end`, one ahead of the one this reads, so a single concurrent
+    /// publish doesn't tear this read - unlike overwriting a `drain`-able slot
+    /// in place; `N+1` publishes during one read would reach this slot.
+    pub fn latest(&self) -> T {
+        let end = self.ring.end.load(Ordering::Acquire);
+        let idx = AtomicRingBuffer::<T, N>::wrap(end + N);
+        self.ring.slice()[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_drain() {
+        let ring = AtomicRingBuffer::<i32, 4>::uninit();
+        ring.init();
+        let (writer, reader) = ring.split();
+
+        assert!(reader.is_empty());
+        writer.push(1);
+        writer.push(2);
+        writer.push(3);
+
+        let collected = reader.drain(|a, b| a.iter().chain(b).copied().collect::<Vec<_>>());
+        assert_eq!(collected, vec![1, 2, 3]);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn full_push_is_rejected() {
+        let ring = AtomicRingBuffer::<i32, 2>::uninit();
+        ring.init();
+        let (writer, _reader) = ring.split();
+
+        assert!(writer.push(1));
+        assert!(writer.push(2));
+        assert!(ring.is_full());
+        assert!(!writer.push(3));
+    }
+
+    #[test]
+    fn latest_reflects_the_most_recent_publish() {
+        let ring = AtomicRingBuffer::<i32, 1>::uninit();
+        ring.init();
+        let (writer, reader) = ring.split();
+
+        assert_eq!(reader.latest(), 0); // default before anything is published
+        writer.publish_latest(1);
+        assert_eq!(reader.latest(), 1);
+        writer.publish_latest(2);
+        assert_eq!(reader.latest(), 2);
+        // reading without draining doesn't block further publishes
+        writer.publish_latest(3);
+        assert_eq!(reader.latest(), 3);
+    }
+
+    #[test]
+    fn drain_across_wrap() {
+        let ring = AtomicRingBuffer::<i32, 3>::uninit();
+        ring.init();
+        let (writer, reader) = ring.split();
+
+        writer.push(1);
+        writer.push(2);
+        reader.drain(|_, _| {});
+        writer.push(3);
+        writer.push(4);
+        writer.push(5);
+
+        let collected = reader.drain(|a, b| a.iter().chain(b).copied().collect::<Vec<_>>());
+        assert_eq!(collected, vec![3, 4, 5]);
+    }
+}
@@ -1,4 +1,8 @@
 #![feature(generic_const_exprs)]
+
+mod atomic_ring;
+pub use atomic_ring::{AtomicRingBuffer, Reader, Writer};
+
 #[derive(Debug)]
 /// A circular buffer that holds at most N element.
 /// The buffer is implemented as a fixed-size array of size 2*N.
@@ -0,0 +1,186 @@
+// configuration persisted in NVS as `key=value` lines, so the device is field-tunable
+// without a reflash; any key that is missing or fails to parse falls back to the
+// compiled default.
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use log::*;
+
+const NVS_KEY : &str = "config";
+const NVS_BUF_LEN : usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub t_low : f32,
+    pub t_high : f32,
+    pub sample_ms : u32,
+    pub buzz_high : f32,
+    pub setpoint : f32,
+    pub kp : f32,
+    pub ki : f32,
+    pub kd : f32,
+    /// 1-wire address of the sensor that drives the buzzer/alarm logic;
+    /// `None` means fall back to whichever sensor was discovered first
+    pub alarm_address : Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            t_low : 20.0,
+            t_high : 25.0,
+            sample_ms : 625,
+            buzz_high : 130.0,
+            setpoint : 121.0,
+            kp : 5.0,
+            ki : 0.1,
+            kd : 1.0,
+            alarm_address : None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `key=value` lines (e.g. `t_low=18.0`) out of NVS, falling back to
+    /// `Default::default()` for any key that is missing or unparseable.
+    pub fn load(nvs : &EspNvs<NvsDefault>) -> Self {
+        let mut buf = [0u8; NVS_BUF_LEN];
+        let text = match nvs.get_str(NVS_KEY, &mut buf) {
+            Ok(Some(text)) => text,
+            Ok(None) => {
+                info!("no stored config, using defaults");
+                return Self::default();
+            },
+            Err(e) => {
+                warn!("failed to read config from NVS: {:?}", e);
+                return Self::default();
+            },
+        };
+        Self::parse(text)
+    }
+
+    /// Parses `key=value` lines, falling back to `Default::default()` for any
+    /// key that is missing or unparseable. Split out from `load` so the parsing
+    /// logic can be exercised without a real NVS partition.
+    fn parse(text : &str) -> Self {
+        let mut config = Self::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let key = key.trim();
+            let value = value.trim();
+            match key {
+                "t_low" => config.t_low = parse_or_default(key, value, config.t_low),
+                "t_high" => config.t_high = parse_or_default(key, value, config.t_high),
+                "sample_ms" => config.sample_ms = parse_or_default(key, value, config.sample_ms),
+                "buzz_high" => config.buzz_high = parse_or_default(key, value, config.buzz_high),
+                "setpoint" => config.setpoint = parse_or_default(key, value, config.setpoint),
+                "kp" => config.kp = parse_or_default(key, value, config.kp),
+                "ki" => config.ki = parse_or_default(key, value, config.ki),
+                "kd" => config.kd = parse_or_default(key, value, config.kd),
+                "alarm_address" => config.alarm_address = u64::from_str_radix(value, 16).ok()
+                    .or_else(|| {
+                        warn!("couldn't parse alarm_address={:?} as hex, using default", value);
+                        config.alarm_address
+                    }),
+                other => warn!("unknown config key {:?}, ignoring", other),
+            }
+        }
+        config
+    }
+
+    /// Serializes back to `key=value` lines and writes them to NVS so runtime changes
+    /// (e.g. new alarm thresholds) survive reboot.
+    pub fn store(&self, nvs : &mut EspNvs<NvsDefault>) -> anyhow::Result<()> {
+        let mut text = format!(
+            "t_low={}\nt_high={}\nsample_ms={}\nbuzz_high={}\nsetpoint={}\nkp={}\nki={}\nkd={}\n",
+            self.t_low, self.t_high, self.sample_ms, self.buzz_high, self.setpoint,
+            self.kp, self.ki, self.kd,
+        );
+        if let Some(addr) = self.alarm_address {
+            text.push_str(&format!("alarm_address={:x}\n", addr));
+        }
+        nvs.set_str(NVS_KEY, &text)?;
+        Ok(())
+    }
+}
+
+fn parse_or_default<T : std::str::FromStr>(key : &str, value : &str, default : T) -> T {
+    value.parse().unwrap_or_else(|_| {
+        warn!("couldn't parse {}={:?}, using default", key, value);
+        default
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trips_every_field() {
+        let config = Config::parse(
+            "t_low=18.5\nt_high=30\nsample_ms=1000\nbuzz_high=135\nsetpoint=110\n\
+             kp=2.5\nki=0.2\nkd=1.5\nalarm_address=1a2b3c\n",
+        );
+        assert_eq!(config, Config {
+            t_low : 18.5,
+            t_high : 30.0,
+            sample_ms : 1000,
+            buzz_high : 135.0,
+            setpoint : 110.0,
+            kp : 2.5,
+            ki : 0.2,
+            kd : 1.5,
+            alarm_address : Some(0x1a2b3c),
+        });
+    }
+
+    #[test]
+    fn parse_falls_back_to_default_on_empty_text() {
+        assert_eq!(Config::parse(""), Config::default());
+    }
+
+    #[test]
+    fn parse_falls_back_per_field_on_unparseable_values() {
+        let config = Config::parse("t_low=not_a_number\nkp=also_bad\nalarm_address=not_hex\n");
+        assert_eq!(config.t_low, Config::default().t_low);
+        assert_eq!(config.kp, Config::default().kp);
+        assert_eq!(config.alarm_address, Config::default().alarm_address);
+    }
+
+    #[test]
+    fn parse_ignores_unknown_keys_and_malformed_lines() {
+        let config = Config::parse("not_a_line\nmystery_key=1\nt_low=5\n");
+        assert_eq!(config.t_low, 5.0);
+        assert_eq!(config.t_high, Config::default().t_high);
+    }
+}
+
+/// WiFi STA credentials, stored separately from `Config` since they're strings
+/// rather than the numeric fields `Config` is `Copy` over.
+#[derive(Debug, Clone)]
+pub struct WifiConfig {
+    pub ssid : String,
+    pub password : String,
+}
+
+impl WifiConfig {
+    const NVS_KEY : &'static str = "wifi";
+    const NVS_BUF_LEN : usize = 128;
+
+    /// Returns `None` if no `ssid=...` line is present in NVS, so callers can
+    /// skip bringing up WiFi entirely on a device with no credentials provisioned.
+    pub fn load(nvs : &EspNvs<NvsDefault>) -> Option<Self> {
+        let mut buf = [0u8; Self::NVS_BUF_LEN];
+        let text = nvs.get_str(Self::NVS_KEY, &mut buf).ok().flatten()?;
+
+        let mut ssid = None;
+        let mut password = None;
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            match key.trim() {
+                "ssid" => ssid = Some(value.trim().to_string()),
+                "password" => password = Some(value.trim().to_string()),
+                other => warn!("unknown wifi config key {:?}, ignoring", other),
+            }
+        }
+        Some(Self { ssid : ssid?, password : password.unwrap_or_default() })
+    }
+}
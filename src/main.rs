@@ -1,9 +1,9 @@
 #![feature(generic_const_exprs)]
-use std::{thread, sync::{atomic::AtomicU8, Arc}, rc::Rc, cell::{RefCell, Cell}, iter::zip, ffi::c_void};
+use std::{thread, sync::{atomic::AtomicU8, Arc, Mutex}, rc::Rc, cell::{RefCell, Cell}, iter::zip, ffi::c_void};
 
 use ds18b20::Ds18b20;
 use embedded_graphics::{primitives::{Polyline, PrimitiveStyle, Primitive}, prelude::Point, pixelcolor::BinaryColor, mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder}, text::Text, Drawable};
-use esp_idf_hal::{prelude::Peripherals, delay::{FreeRtos, Ets}, gpio::{PinDriver, AnyIOPin}, i2c::{config::Config, I2cDriver}, units::Hertz};
+use esp_idf_hal::{prelude::Peripherals, delay::{FreeRtos, Ets}, gpio::{PinDriver, AnyIOPin}, i2c::{config::Config, I2cDriver}, units::Hertz, ledc::{LedcDriver, LedcTimerDriver, config::TimerConfig}};
 use esp_idf_svc::systime::EspSystemTime;
 use esp_idf_sys as _; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
 use anyhow::anyhow;
@@ -16,6 +16,17 @@ use itertools::Itertools;
 
 use circular_buffer::*;
 
+mod config;
+use config::Config as AppConfig;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+
+mod pid;
+use pid::Pid;
+
+mod state;
+use state::State;
+
+mod net;
 
 // TODO hardware gets stuck see README.md
 // TODO name the states ie. enum instead of u8
@@ -63,14 +74,15 @@ fn result_to_either<T, E>(x: Result<T, E>) -> itertools::Either<T, E> {
     }
 }
 
-// I want to change this to get temperatures for all of the sensors,
-// sorted by address, should these addresses be returned?
-fn mk_get_temp(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Result<Vec<f32>>>> {
+// gets temperatures for every sensor found on the bus, sorted by address; the
+// address is returned alongside each reading since it's the stable key callers
+// use to tell sensors apart across scans (a DS18B20 has no other identity)
+fn mk_get_temp(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Result<Vec<(one_wire_bus::Address, f32)>>>> {
 
     let pindriver = PinDriver::input_output_od(pin)?;
     let mut one_wire_bus = OneWire::new(pindriver).map_err(|_| anyhow!("Failed to initialize 1-wire bus"))?;
 
-    let f = move || -> anyhow::Result<Vec<f32>> {
+    let f = move || -> anyhow::Result<Vec<(one_wire_bus::Address, f32)>> {
 
         let (mut addrs,errs) : (Vec<_>, Vec<_>) = one_wire_bus.devices(false, &mut Ets)
               .partition(|x| x.is_ok());
@@ -78,10 +90,11 @@ fn mk_get_temp(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Resu
         addrs.sort_by_key(|a| a.unwrap().0);
 
         let reads : Vec<_> = addrs.into_iter().map(|addr| {
-          let dev = Ds18b20::new::<anyhow::Error>(addr.unwrap())
+          let (address, family) = addr.unwrap();
+          let dev = Ds18b20::new::<anyhow::Error>((address, family))
               .map_err(|x| anyhow!("onewire can't init ds18b20 {:?}", x))?;
 
-          info!("addr: {:?}", addr);
+          info!("addr: {:?}", address);
           dev.start_temp_measurement(
               &mut one_wire_bus,
               &mut Ets)
@@ -90,7 +103,7 @@ fn mk_get_temp(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Resu
               &mut one_wire_bus,
               &mut Ets)
               .map_err(|x| anyhow!("onewire can't finish measurment {:?}", x))
-              .map(|x| x.temperature)
+              .map(|x| (address, x.temperature))
         }).filter_map(|x| x.ok()).collect();
 
         Ok(reads)
@@ -98,6 +111,88 @@ fn mk_get_temp(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Resu
     Ok(Box::new(f))
 }
 
+/// number of edge transitions in one DHT22 reply frame: an 80us-low/80us-high
+/// preamble (2 edges), then 40 data bits each contributing a low-to-high edge
+/// and a high-to-low edge, plus one trailing edge to close out bit 39's high pulse
+const DHT22_EDGES : usize = 3 + 40*2;
+/// give up waiting for the next edge after this long without one
+const DHT22_TIMEOUT_US : u64 = 10_000;
+/// a data bit's high pulse is `1` if longer than this, `0` otherwise: splits the
+/// ~26-28us "0" pulses from the ~70us "1" pulses
+const DHT22_BIT_THRESHOLD_US : u64 = 50;
+/// the DHT22 datasheet requires at least this long between reads; polling it
+/// faster (the display loop runs every `sample_ms`, default 625ms) just gets
+/// stale/NACK frames
+const DHT22_MIN_INTERVAL_MS : u64 = 2_000;
+
+// TODO this busy-polls instead of using a GPIO interrupt/RMT peripheral, so a
+// long enough scheduling hiccup mid-frame will corrupt or time out the read
+fn mk_get_humidity(pin : AnyIOPin) -> anyhow::Result<Box<dyn FnMut() -> anyhow::Result<f32>>> {
+    let mut pindriver = PinDriver::input_output_od(pin)?;
+    pindriver.set_high()?;
+    let mut last_read : Option<(u64, f32)> = None;
+
+    let f = move || -> anyhow::Result<f32> {
+        // too soon since the last read: return the last good value rather than
+        // re-polling the sensor faster than it can honor
+        let now_ms = EspSystemTime.now().as_millis() as u64;
+        if let Some((last_ms, last_humidity)) = last_read {
+            if now_ms - last_ms < DHT22_MIN_INTERVAL_MS {
+                return Ok(last_humidity);
+            }
+        }
+
+        // handshake: host pulls the line low ~1ms, then releases it; the sensor
+        // (held up by an external pull-up) takes over to send its reply
+        pindriver.set_low()?;
+        FreeRtos::delay_ms(1);
+        pindriver.set_high()?;
+
+        // record every edge timestamp until the whole frame has arrived or we time out
+        let mut edges = Vec::with_capacity(DHT22_EDGES);
+        let mut level = pindriver.is_high();
+        let start = EspSystemTime.now().as_micros() as u64;
+        while edges.len() < DHT22_EDGES {
+            let now_level = pindriver.is_high();
+            let now = EspSystemTime.now().as_micros() as u64;
+            if now_level != level {
+                edges.push(now);
+                level = now_level;
+            }
+            if now - start > DHT22_TIMEOUT_US {
+                return Err(anyhow!("DHT22 timed out after {} of {} edges", edges.len(), DHT22_EDGES));
+            }
+        }
+
+        // skip the 2 preamble edges (ack low start, ack high start); each bit then
+        // contributes a low start (edges[2+2i]) and a high start (edges[3+2i]), and
+        // its value is carried by the width of that high pulse, from its low-to-high
+        // edge to the following high-to-low edge (the next bit's low start)
+        let mut bits = [0u8; 40];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            let high_start = edges[3 + 2*i];
+            let high_end = edges[4 + 2*i];
+            *bit = (high_end - high_start > DHT22_BIT_THRESHOLD_US) as u8;
+        }
+
+        let byte = |i : usize| bits[i*8..i*8+8].iter().fold(0u8, |acc, &b| (acc << 1) | b);
+        let h_hi = byte(0);
+        let h_lo = byte(1);
+        let t_hi = byte(2);
+        let t_lo = byte(3);
+        let checksum = byte(4);
+
+        if h_hi.wrapping_add(h_lo).wrapping_add(t_hi).wrapping_add(t_lo) != checksum {
+            return Err(anyhow!("DHT22 checksum mismatch"));
+        }
+
+        let humidity = u16::from_be_bytes([h_hi, h_lo]) as f32 / 10.0;
+        last_read = Some((now_ms, humidity));
+        Ok(humidity)
+    };
+    Ok(Box::new(f))
+}
+
 
 fn mk_display<'d>(i2c_driver : I2cDriver<'d>) ->
     anyhow::Result<Ssd1306<I2CInterface<I2cDriver<'d>>,DisplaySize128x32,BufferedGraphicsMode<DisplaySize128x32>>> {
@@ -108,7 +203,22 @@ fn mk_display<'d>(i2c_driver : I2cDriver<'d>) ->
     Ok(display)
 }
 
-const W_TEXT : usize = 32;
+pub const W_TEXT : usize = 32;
+
+// one reading for one sensor, as handed from the acquisition thread to the
+// display thread; the address is carried as a raw u64 rather than
+// `one_wire_bus::Address` so this can satisfy `AtomicRingBuffer`'s `Default` bound
+#[derive(Debug, Clone, Copy, Default)]
+struct Reading {
+    addr : u64,
+    temp : f32,
+}
+
+// samples in flight between the acquisition thread and the display thread;
+// generous relative to the ~750ms 1-wire conversion time and the ~625ms display period
+const RING_CAPACITY : usize = 16;
+
+static TEMP_RING : AtomicRingBuffer<Reading, RING_CAPACITY> = AtomicRingBuffer::uninit();
 
 fn main() -> anyhow::Result<()>{
     // It is necessary to call this function once. Otherwise some patches to the runtime
@@ -123,9 +233,45 @@ fn main() -> anyhow::Result<()>{
     info!("initializing peripherals");
     let peripherals = Peripherals::take()?;
 
+    info!("loading config from NVS");
+    let nvs_partition = EspDefaultNvsPartition::take()?;
+    let nvs = EspNvs::new(nvs_partition, "cooker", true)?;
+    let mut app_config = AppConfig::load(&nvs);
+    let wifi_creds = config::WifiConfig::load(&nvs);
+    let nvs = Arc::new(Mutex::new(nvs));
+
+    info!("starting networking");
+    let sysloop = esp_idf_svc::eventloop::EspSystemEventLoop::take()?;
+    // `_wifi`/`_http_server` just need to live as long as `main`'s `loop {}`, which is forever
+    let (mut snapshot_writer, _wifi, _http_server) =
+        match net::start(peripherals.modem, sysloop, nvs.clone(), wifi_creds) {
+            Ok((wifi, server, writer)) => (Some(writer), Some(wifi), Some(server)),
+            Err(e) => {
+                warn!("failed to start networking: {:?}", e);
+                (None, None, None)
+            },
+        };
+
     let mut state: State<{128-W_TEXT}> = State::new();
 
+    TEMP_RING.init();
+    let (temp_writer, temp_reader) = TEMP_RING.split();
+
+    info!("starting acquisition thread");
     let mut get_temp = mk_get_temp(AnyIOPin::from(peripherals.pins.gpio13))?;
+    thread::spawn(move || loop {
+        match get_temp() {
+            Ok(readings) => {
+                info!("TEMPS, {}", readings.iter().map(|(a, t)| format!("{:?}={}", a, t)).join(","));
+                for (address, temp) in readings {
+                    if !temp_writer.push(Reading{ addr : address.0, temp }) {
+                        warn!("temperature ring buffer full, dropping sample for {:?}", address);
+                    }
+                }
+            },
+            Err(e) => warn!("get_temp error: {:?}", e),
+        }
+    });
 
     info!("initializing i2c display");
     let i2c_config = Config::new().baudrate(Hertz(1_000_000));
@@ -140,33 +286,101 @@ fn main() -> anyhow::Result<()>{
         .build();
 
     info!("initializing buzzer");
-    let _set_buzz = mk_buzzer(AnyIOPin::from(peripherals.pins.gpio0))?;
+    let mut set_buzz = mk_buzzer(AnyIOPin::from(peripherals.pins.gpio0))?;
+
+    info!("initializing humidity sensor");
+    let mut get_humidity = mk_get_humidity(AnyIOPin::from(peripherals.pins.gpio4))?;
+
+    info!("initializing heater PWM");
+    let ledc_timer = LedcTimerDriver::new(peripherals.ledc.timer0, &TimerConfig::new())?;
+    let mut heater = LedcDriver::new(peripherals.ledc.channel0, ledc_timer, peripherals.pins.gpio6)?;
+    let heater_max_duty = heater.get_max_duty();
+    let mut pid = Pid::new(app_config.kp, app_config.ki, app_config.kd);
 
-    info!("starting temperature/display thread");
-    // loop to read temperature and display it,
-    let times : CircularBuffer<u128, 2> = CircularBuffer::new();
+    // how often the display loop re-reads NVS for bounds/setpoint changes posted
+    // through the HTTP server, rather than on every ~625ms frame
+    const CONFIG_POLL_INTERVAL_MS : u64 = 5_000;
+    let mut last_config_poll_ms = EspSystemTime.now().as_millis() as u64;
+
+    info!("starting display loop");
+    // loop to drain whatever readings arrived since the last frame and display them,
     loop {
-        // take a temperature measurement
-        let temperatures = get_temp()?;
-        let temperature = temperatures[0];
-        info!("TEMPS, {}", temperatures.into_iter().map(|x| x.to_string()).join(","));
-        state.push(temperature);
+        // consume every sample the acquisition thread pushed since the last frame
+        let samples : Vec<Reading> = temp_reader.drain(|a, b| a.iter().chain(b).copied().collect());
+
+        // a scan's addresses are who's still plugged in; drop any sensor missing
+        // from it so an unplugged probe's graph doesn't linger
+        let seen_addrs : Vec<u64> = samples.iter().map(|r| r.addr).unique().collect();
+        if !seen_addrs.is_empty() {
+            state.retain_addresses(&seen_addrs);
+        }
+        for sample in &samples {
+            state.push(sample.addr, sample.temp, &app_config);
+        }
         state.push_time();
 
+        // the configured address drives the alarm/setpoint readout, falling back
+        // to whichever sensor was discovered first
+        let alarm_addr = app_config.alarm_address
+            .filter(|addr| state.sensors.contains_key(addr))
+            .or_else(|| state.sensors.keys().min().copied());
+        let alarm = alarm_addr.and_then(|addr| state.sensors.get(&addr));
+        let temperature = alarm.and_then(|h| h.past_temperatures.head()).unwrap_or(app_config.t_low);
+        let t_low = alarm.map(|h| h.t_low).unwrap_or(app_config.t_low);
+        let t_high = alarm.map(|h| h.t_high).unwrap_or(app_config.t_high);
+        // copied out now so the snapshot published below doesn't need `alarm`'s
+        // borrow of `state` to outlive the `state.push_time()` calls in between
+        let history_points : Vec<f32> = alarm.map(|h| h.past_temperatures.into_iter().collect()).unwrap_or_default();
+
         let mut rounded_at = |n : f32, y|
             Text::new(&format!("{:3}", (n * 10.0).round() / 10.0), Point{ x : 0, y }, text_style)
             .draw(&mut display)
             .map_err(|x| anyhow!("display error {:?}", x));
 
-        // on the left side draw the temperature bounds
-        // at the top and bottom, and the current temperature in the middle
+        // on the left side draw the alarm sensor's bounds
+        // at the top and bottom, and its current temperature in the middle
         rounded_at(temperature, 19)?;
-        rounded_at(state.t_high, 7)?;
-        rounded_at(state.t_low, 31)?;
+        rounded_at(t_high, 7)?;
+        rounded_at(t_low, 31)?;
+
+        // ambient humidity, in the top-right corner next to the graph
+        match get_humidity() {
+            Ok(humidity) => {
+                Text::new(&format!("{:.0}%", humidity), Point{ x : 104, y : 7 }, text_style)
+                    .draw(&mut display)
+                    .map_err(|x| anyhow!("display error {:?}", x))?;
+            },
+            Err(e) => warn!("get_humidity error: {:?}", e),
+        }
+
+        // overlay every sensor's graph to the right of the numbers
+        for history in state.sensors.values() {
+            Polyline::new(history.past_points.as_slice())
+                .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+                .draw(&mut display)
+                .map_err(|x| anyhow!("display error {:?}", x))?;
+        }
+
+        // sound the buzzer when the alarm sensor crosses the configured high threshold
+        set_buzz(if temperature > app_config.buzz_high { 2 } else { 0 });
+
+        // drive the heater toward the setpoint using real elapsed time as dt, but
+        // only when the alarm sensor actually has a reading: without one,
+        // `temperature` is just `app_config.t_low` and feeding that error to the
+        // PID would drive the heater full-on with no real feedback
+        let dt = state.time_delta().unwrap_or(0) as f32 / 1000.0;
+        let duty = match alarm {
+            Some(_) => pid.step(app_config.setpoint, temperature, dt),
+            None => {
+                warn!("no temperature reading for the alarm sensor, forcing heater off");
+                pid.reset();
+                0.0
+            },
+        };
+        heater.set_duty((duty / 100.0 * heater_max_duty as f32) as u32)?;
+        info!("heater duty {:.0}% toward setpoint {:.1}", duty, app_config.setpoint);
 
-        // draw the temperature graph to the right of the numbers
-        Polyline::new(state.past_points.as_slice())
-            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        Text::new(&format!("{:.0}%->{:.0}", duty, app_config.setpoint), Point{ x : 64, y : 19 }, text_style)
             .draw(&mut display)
             .map_err(|x| anyhow!("display error {:?}", x))?;
 
@@ -175,103 +389,39 @@ fn main() -> anyhow::Result<()>{
 
         state.push_time();
 
-
-        let wait = 625 - state.time_delta().unwrap_or(0) as i64;
-        if wait > 0 { 
-            info!("waiting {}ms", wait);
-            FreeRtos::delay_ms(wait as u32);
-        };
-
-        display.clear();
-    }
-}
-
-
-
-// the state used to draw the temperature graph
-// whose bounds adjust to fit
-// TODO "times" could be longer to account for time spent setting up the delay
-struct State<const N: usize> where [(); 2*N]: {
-   past_temperatures : CircularBuffer<f32, N>,
-   past_points : CircularBuffer<Point, N>,
-   times : CircularBuffer<u128, 2>,
-   t_low : f32,
-   t_high : f32,
-}
-
-impl<const N : usize> State<N> where [(); 2*N]: {
-    fn new() -> Self {
-        Self {
-            past_temperatures : CircularBuffer::new(),
-            past_points : CircularBuffer::new(),
-            times : CircularBuffer::<_,2>::new(),
-            t_low : 20.0,
-            t_high : 25.0,
+        // `t_low`/`t_high` above are the alarm sensor's auto-fit graph scale, which
+        // expands every frame a reading pushes past it; deliberately not written
+        // back into `app_config`/NVS here, since doing so clobbered bounds posted
+        // through the HTTP `/bounds` endpoint within a frame or two of being set
+
+        // pick up bounds/setpoint changes posted to the HTTP server, polled on a
+        // slow interval rather than every frame to spare the NVS flash cells
+        let now_ms = EspSystemTime.now().as_millis() as u64;
+        if now_ms - last_config_poll_ms >= CONFIG_POLL_INTERVAL_MS {
+            app_config = AppConfig::load(&nvs.lock().unwrap());
+            last_config_poll_ms = now_ms;
         }
-    }
-
-    fn time_delta(&self) -> Option<u128> {
-        Some(self.times.head()? - self.times.last()?)
-    }
-
-    fn push_time(&mut self) {
-        self.times.push(EspSystemTime.now().as_millis());
-    }
 
-    /// add a new temperature to the state
-    /// and update the temperature bounds
-    /// and the points to draw
-    /// somewhat buggy: it can cut off a recent peak
-    /// when the oldest temperatures are nealy identical
-    fn push(&mut self, temp : f32) {
-        let mut dirty = false;
-        let dropped = self.past_temperatures.last();
-        let was_full = self.past_temperatures.is_full();
-        self.past_temperatures.push(temp);
-
-        // expand temperature bounds
-        if temp < self.t_low {
-            self.t_low = temp;
-            dirty = true;
-        }
-        if temp > self.t_low {
-            self.t_high = temp;
-            dirty = true;
-        }
-
-        let may_drop_bound_temp = was_full && [self.t_low, self.t_high].iter()
-                .any(|&x| dropped.map(|o| (x-o).abs() < 0.1).unwrap_or(false) );
-
-        // recalculate temp_bounds
-        if may_drop_bound_temp {
-            let mut iter = self.past_temperatures.into_iter();
-            if let Some(t0) = iter.next() {
-                self.t_low = t0-0.05;
-                self.t_high = t0+0.05;
-                for t in iter {
-                    if t < self.t_low {
-                        self.t_low = t;
-                    }
-                    if t > self.t_high {
-                        self.t_high = t;
-                    }
-                }
+        // publish this frame for the HTTP /history endpoint to read without
+        // touching `state` from another thread
+        if let Some(writer) = &mut snapshot_writer {
+            let mut snapshot = net::Snapshot::default();
+            snapshot.len = history_points.len().min(net::SNAPSHOT_HISTORY_LEN);
+            for (slot, t) in snapshot.temperatures.iter_mut().zip(history_points.iter()) {
+                *slot = *t;
             }
+            snapshot.t_low = t_low;
+            snapshot.t_high = t_high;
+            snapshot.setpoint = app_config.setpoint;
+            writer.publish_latest(snapshot);
         }
 
-        // update past_points to account for the new bounds
-        let scale = |x| (31.0 - 30.0 * (x - self.t_low) / (self.t_high - self.t_low)) as i32;
-
-        // couldn't get std::iter::zip to do this
-        // because mutating the equivalent of p there didn't change the value in past_points
-        self.past_points.zip_with(&self.past_temperatures, |p, &t| {
-            p.x+=1; // shift to the right
-            if dirty { // when temp_bounds changed, recalculate y
-                p.y = scale(t);
-        }});
+        let wait = app_config.sample_ms as i64 - state.time_delta().unwrap_or(0) as i64;
+        if wait > 0 {
+            info!("waiting {}ms", wait);
+            FreeRtos::delay_ms(wait as u32);
+        };
 
-        // add the newest point
-        self.past_points.push(Point{ x: W_TEXT as i32, y: scale(temp) });
+        display.clear();
     }
 }
-
@@ -0,0 +1,120 @@
+// optional networking subsystem: brings up WiFi STA from NVS-provisioned
+// credentials and serves the in-memory temperature history over HTTP. The
+// acquisition/display loop and the HTTP handler run on different threads, so
+// rather than share `State` directly they're paired through a single-slot
+// `AtomicRingBuffer` the display loop publishes a snapshot into every frame
+// via `Writer::publish_latest`, whose double-buffering lets the handler read
+// the latest snapshot with `Reader::latest` without ever blocking the loop;
+// a read only races a publish if two publishes land while it is copying.
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use embedded_svc::http::Method;
+use embedded_svc::io::{Read as _, Write as _};
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::http::server::{Configuration as HttpServerConfig, EspHttpServer};
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration as WifiConfig, EspWifi};
+use log::*;
+
+use circular_buffer::{AtomicRingBuffer, Writer};
+
+use crate::config::{Config, WifiConfig as WifiCreds};
+
+/// history is reported at most this many points; matches `State`'s default capacity
+pub const SNAPSHOT_HISTORY_LEN : usize = 96;
+
+/// one frame's worth of data, copied out into a form cheap enough to live in
+/// the lock-free ring buffer (no `Vec`, so it can be `Copy`)
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    pub len : usize,
+    pub temperatures : [f32; SNAPSHOT_HISTORY_LEN],
+    pub t_low : f32,
+    pub t_high : f32,
+    pub setpoint : f32,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self { len : 0, temperatures : [0.0; SNAPSHOT_HISTORY_LEN], t_low : 0.0, t_high : 0.0, setpoint : 0.0 }
+    }
+}
+
+impl Snapshot {
+    fn to_json(self) -> String {
+        let points = self.temperatures[..self.len.min(SNAPSHOT_HISTORY_LEN)].iter()
+            .map(|t| t.to_string()).collect::<Vec<_>>().join(",");
+        format!(
+            r#"{{"past_temperatures":[{}],"t_low":{},"t_high":{},"setpoint":{}}}"#,
+            points, self.t_low, self.t_high, self.setpoint,
+        )
+    }
+}
+
+static SNAPSHOT_RING : AtomicRingBuffer<Snapshot, 1> = AtomicRingBuffer::uninit();
+
+/// Brings up WiFi STA (skipped with a warning if no credentials are provisioned)
+/// and the HTTP server, and returns the `Writer` half of the snapshot ring for
+/// the display loop to publish into once per frame. The returned `EspWifi`/
+/// `EspHttpServer` must be kept alive for as long as networking should run.
+pub fn start(
+    modem : Modem,
+    sysloop : EspSystemEventLoop,
+    nvs : Arc<Mutex<EspNvs<NvsDefault>>>,
+    wifi_creds : Option<WifiCreds>,
+) -> anyhow::Result<(BlockingWifi<EspWifi<'static>>, EspHttpServer<'static>, Writer<'static, Snapshot, 1>)> {
+    SNAPSHOT_RING.init();
+    let (snapshot_writer, snapshot_reader) = SNAPSHOT_RING.split();
+
+    let mut wifi = BlockingWifi::wrap(EspWifi::new(modem, sysloop.clone(), None)?, sysloop)?;
+    match wifi_creds {
+        Some(creds) => {
+            wifi.set_configuration(&WifiConfig::Client(ClientConfiguration {
+                ssid : creds.ssid.as_str().try_into().map_err(|_| anyhow!("ssid too long for WiFi config"))?,
+                password : creds.password.as_str().try_into().map_err(|_| anyhow!("password too long for WiFi config"))?,
+                ..Default::default()
+            }))?;
+            wifi.start()?;
+            wifi.connect()?;
+            wifi.wait_netif_up()?;
+            info!("wifi connected");
+        },
+        None => warn!("no wifi credentials in NVS (wifi.ssid=...), networking stays down"),
+    }
+
+    let mut server = EspHttpServer::new(&HttpServerConfig::default())?;
+
+    server.fn_handler("/history", Method::Get, move |request| {
+        let snapshot = snapshot_reader.latest();
+        let body = snapshot.to_json();
+        request.into_ok_response()?.write_all(body.as_bytes())?;
+        Ok(())
+    })?;
+
+    let post_nvs = nvs.clone();
+    server.fn_handler("/bounds", Method::Post, move |mut request| {
+        let mut body = [0u8; 256];
+        let len = request.read(&mut body)?;
+        let text = std::str::from_utf8(&body[..len]).unwrap_or("");
+
+        let mut nvs = post_nvs.lock().map_err(|_| anyhow!("nvs lock poisoned"))?;
+        let mut config = Config::load(&nvs);
+        for pair in text.split('&') {
+            let Some((key, value)) = pair.split_once('=') else { continue };
+            match key {
+                "t_low" => if let Ok(v) = value.parse() { config.t_low = v },
+                "t_high" => if let Ok(v) = value.parse() { config.t_high = v },
+                "setpoint" => if let Ok(v) = value.parse() { config.setpoint = v },
+                _ => warn!("unknown /bounds field {:?}, ignoring", key),
+            }
+        }
+        config.store(&mut nvs)?;
+
+        request.into_ok_response()?.write_all(b"ok")?;
+        Ok(())
+    })?;
+
+    Ok((wifi, server, snapshot_writer))
+}
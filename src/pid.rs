@@ -0,0 +1,91 @@
+// PID control of the heating element's duty cycle, stepped once per loop iteration
+// using the real elapsed time between frames as `dt`.
+pub struct Pid {
+    pub kp : f32,
+    pub ki : f32,
+    pub kd : f32,
+    integral : f32,
+    prev_temperature : Option<f32>,
+}
+
+impl Pid {
+    pub fn new(kp : f32, ki : f32, kd : f32) -> Self {
+        Self { kp, ki, kd, integral : 0.0, prev_temperature : None }
+    }
+
+    /// Advances the controller by `dt` seconds given the current `temperature` and
+    /// `setpoint`, and returns the duty cycle clamped to `0..=100`%.
+    ///
+    /// The integral term alone is clamped to `0..=100` (anti-windup), and the
+    /// derivative is taken on the measurement rather than the error so a setpoint
+    /// change doesn't kick the output.
+    pub fn step(&mut self, setpoint : f32, temperature : f32, dt : f32) -> f32 {
+        let error = setpoint - temperature;
+        self.integral += error * dt;
+        let integral_term = (self.ki * self.integral).clamp(0.0, 100.0);
+        if self.ki != 0.0 {
+            self.integral = integral_term / self.ki;
+        }
+
+        let derivative = match self.prev_temperature {
+            Some(prev) if dt > 0.0 => -(temperature - prev) / dt,
+            _ => 0.0,
+        };
+        self.prev_temperature = Some(temperature);
+
+        (self.kp * error + integral_term + self.kd * derivative).clamp(0.0, 100.0)
+    }
+
+    /// Clears the integral accumulator and the derivative's previous-measurement
+    /// memory. Call this when the heater is being forced off due to a missing
+    /// measurement, so the controller doesn't resume with a stale integral once
+    /// a reading comes back.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.prev_temperature = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proportional_only_tracks_error() {
+        let mut pid = Pid::new(2.0, 0.0, 0.0);
+        // error = 10, kp = 2 -> 20%
+        assert_eq!(pid.step(121.0, 111.0, 1.0), 20.0);
+    }
+
+    #[test]
+    fn integral_accumulates_and_clamps_to_100_percent() {
+        let mut pid = Pid::new(0.0, 1.0, 0.0);
+        // error = 50 each second; integral_term = ki * integral grows then clamps
+        assert_eq!(pid.step(121.0, 71.0, 1.0), 50.0);
+        assert_eq!(pid.step(121.0, 71.0, 1.0), 100.0);
+        // anti-windup: a further step at the same error stays clamped, not overshooting
+        assert_eq!(pid.step(121.0, 71.0, 1.0), 100.0);
+    }
+
+    #[test]
+    fn derivative_is_on_measurement_not_error() {
+        let mut pid = Pid::new(0.0, 0.0, 1.0);
+        pid.step(121.0, 100.0, 1.0);
+        // temperature rose 5 degrees over 1s while the setpoint jumped 121 -> 200, so
+        // the error rose by 74 but the derivative on the measurement is -5,
+        // clamped to 0 since a falling contribution can't push duty below the floor
+        assert_eq!(pid.step(200.0, 105.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn reset_clears_integral_and_derivative_memory() {
+        let mut pid = Pid::new(0.0, 1.0, 1.0);
+        pid.step(121.0, 71.0, 1.0);
+        pid.reset();
+        // with integral and prev_temperature cleared, the first step after reset
+        // behaves as if freshly constructed: no derivative kick, integral restarts at 0,
+        // so error = 10 gives 10% (a kept integral would give 60%, a kept
+        // prev_temperature a -40 derivative)
+        assert_eq!(pid.step(121.0, 111.0, 1.0), 10.0);
+    }
+}
@@ -0,0 +1,123 @@
+// state used to draw the temperature graph(s), whose bounds adjust to fit;
+// one `SensorHistory` per discovered DS18B20, keyed by its stable 1-wire address
+// so sensors can appear/disappear between scans without disturbing the others.
+use std::collections::HashMap;
+use embedded_graphics::prelude::Point;
+use esp_idf_svc::systime::EspSystemTime;
+
+use circular_buffer::CircularBuffer;
+
+use crate::config::Config;
+use crate::W_TEXT;
+
+// TODO "times" could be longer to account for time spent setting up the delay
+pub struct State<const N: usize> where [(); 2*N]: {
+    pub sensors : HashMap<u64, SensorHistory<N>>,
+    times : CircularBuffer<u128, 2>,
+}
+
+impl<const N : usize> State<N> where [(); 2*N]: {
+    pub fn new() -> Self {
+        Self {
+            sensors : HashMap::new(),
+            times : CircularBuffer::<_, 2>::new(),
+        }
+    }
+
+    pub fn time_delta(&self) -> Option<u128> {
+        Some(self.times.head()? - self.times.last()?)
+    }
+
+    pub fn push_time(&mut self) {
+        self.times.push(EspSystemTime.now().as_millis());
+    }
+
+    /// Drops sensors not present in `addrs` (a full scan's addresses), so a probe
+    /// that's unplugged stops being drawn instead of showing a stale graph.
+    pub fn retain_addresses(&mut self, addrs : &[u64]) {
+        self.sensors.retain(|addr, _| addrs.contains(addr));
+    }
+
+    /// Adds a new temperature reading for `addr`, creating its history (seeded from
+    /// `config`'s shared bounds) the first time that address is seen.
+    pub fn push(&mut self, addr : u64, temp : f32, config : &Config) {
+        self.sensors.entry(addr)
+            .or_insert_with(|| SensorHistory::new(config))
+            .push(temp);
+    }
+}
+
+// per-sensor temperature history and the bounds its graph is scaled to
+pub struct SensorHistory<const N: usize> where [(); 2*N]: {
+    pub past_temperatures : CircularBuffer<f32, N>,
+    pub past_points : CircularBuffer<Point, N>,
+    pub t_low : f32,
+    pub t_high : f32,
+}
+
+impl<const N : usize> SensorHistory<N> where [(); 2*N]: {
+    fn new(config : &Config) -> Self {
+        Self {
+            past_temperatures : CircularBuffer::new(),
+            past_points : CircularBuffer::new(),
+            t_low : config.t_low,
+            t_high : config.t_high,
+        }
+    }
+
+    /// add a new temperature to the history
+    /// and update the temperature bounds
+    /// and the points to draw
+    /// somewhat buggy: it can cut off a recent peak
+    /// when the oldest temperatures are nealy identical
+    fn push(&mut self, temp : f32) {
+        let mut dirty = false;
+        let dropped = self.past_temperatures.last();
+        let was_full = self.past_temperatures.is_full();
+        self.past_temperatures.push(temp);
+
+        // expand temperature bounds
+        if temp < self.t_low {
+            self.t_low = temp;
+            dirty = true;
+        }
+        if temp > self.t_high {
+            self.t_high = temp;
+            dirty = true;
+        }
+
+        let may_drop_bound_temp = was_full && [self.t_low, self.t_high].iter()
+                .any(|&x| dropped.map(|o| (x-o).abs() < 0.1).unwrap_or(false) );
+
+        // recalculate temp_bounds
+        if may_drop_bound_temp {
+            let mut iter = self.past_temperatures.into_iter();
+            if let Some(t0) = iter.next() {
+                self.t_low = t0-0.05;
+                self.t_high = t0+0.05;
+                for t in iter {
+                    if t < self.t_low {
+                        self.t_low = t;
+                    }
+                    if t > self.t_high {
+                        self.t_high = t;
+                    }
+                }
+            }
+        }
+
+        // update past_points to account for the new bounds
+        let scale = |x| (31.0 - 30.0 * (x - self.t_low) / (self.t_high - self.t_low)) as i32;
+
+        // couldn't get std::iter::zip to do this
+        // because mutating the equivalent of p there didn't change the value in past_points
+        self.past_points.zip_with(&self.past_temperatures, |p, &t| {
+            p.x+=1; // shift to the right
+            if dirty { // when temp_bounds changed, recalculate y
+                p.y = scale(t);
+        }});
+
+        // add the newest point
+        self.past_points.push(Point{ x: W_TEXT as i32, y: scale(temp) });
+    }
+}